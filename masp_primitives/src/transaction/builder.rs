@@ -1,5 +1,6 @@
 //! Structs for building transactions.
 
+use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::error;
 use std::fmt;
@@ -14,7 +15,12 @@ use crate::{
     convert::AllowedConversion,
     keys::OutgoingViewingKey,
     memo::MemoBytes,
-    sapling::{self, prover::TxProver, value::NoteValue, Diversifier, Note, PaymentAddress},
+    sapling::{
+        self,
+        prover::{OutputProver, SpendProver, TxProver},
+        value::NoteValue,
+        Diversifier, Note, PaymentAddress,
+    },
     transaction::{
         components::{
             amount::{Amount, BalanceError, MAX_MONEY},
@@ -24,7 +30,7 @@ use crate::{
             },
             transparent::{self, builder::TransparentBuilder},
         },
-        fees::FeeRule,
+        fees::{fixed, FeeRule},
         sighash::{signature_hash, SignableInput},
         txid::TxIdDigester,
         Transaction, TransactionData, TransparentAddress, TxVersion, Unauthorized,
@@ -34,11 +40,42 @@ use crate::{
 
 #[cfg(feature = "transparent-inputs")]
 use crate::transaction::components::transparent::TxOut;
+#[cfg(feature = "transparent-inputs")]
+use secp256k1;
+
+#[cfg(feature = "orchard")]
+use crate::transaction::components::orchard::{self as orchard_builder, OrchardBuilder};
 
 /// Since Blossom activation, the default transaction expiry delta should be 40 blocks.
 /// <https://zips.z.cash/zip-0203#changes-for-blossom>
 const DEFAULT_TX_EXPIRY_DELTA: u32 = 40;
 
+/// The minimum number of Sapling outputs historically emitted by shielded
+/// transactions, so that the presence of a real output does not by itself leak
+/// the shape of a transaction.
+const MIN_SHIELDED_OUTPUTS: usize = 2;
+
+/// Policy controlling how many dummy Sapling outputs the builder appends in
+/// order to obscure the real output count of a shielded transaction.
+///
+/// Padding is only applied when the Sapling bundle is non-empty; a transaction
+/// with no Sapling spends or outputs is left untouched so that fully
+/// transparent transactions remain minimal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum PaddingRule {
+    /// Emit exactly the outputs added by the caller.
+    None,
+    /// Pad the Sapling output count up to the given target with zero-value
+    /// dummy outputs that decrypt to nobody.
+    PadTo(usize),
+}
+
+impl Default for PaddingRule {
+    fn default() -> Self {
+        PaddingRule::PadTo(MIN_SHIELDED_OUTPUTS)
+    }
+}
+
 /// Errors that can occur during transaction construction.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error<FeeError> {
@@ -56,6 +93,16 @@ pub enum Error<FeeError> {
     TransparentBuild(transparent::builder::Error),
     /// An error occurred in constructing the Sapling parts of a transaction.
     SaplingBuild(sapling_builder::Error),
+    /// A memo was supplied for a transparent recipient, which cannot carry one.
+    MemoForbidden,
+    /// No memo was supplied for a shielded recipient, which requires one.
+    MemoRequired,
+    /// An error occurred in constructing the Orchard parts of a transaction.
+    #[cfg(feature = "orchard")]
+    OrchardBuild(orchard_builder::Error),
+    /// The transaction requires a change output for one or more assets, but no
+    /// change address was supplied to [`Builder::build_with_change`].
+    ChangeError,
 }
 
 impl<FE: fmt::Display> fmt::Display for Error<FE> {
@@ -75,10 +122,163 @@ impl<FE: fmt::Display> fmt::Display for Error<FE> {
             Error::Fee(e) => write!(f, "An error occurred in fee calculation: {}", e),
             Error::TransparentBuild(err) => err.fmt(f),
             Error::SaplingBuild(err) => err.fmt(f),
+            Error::MemoForbidden => write!(f, "A memo cannot be sent to a transparent recipient"),
+            Error::MemoRequired => write!(f, "A memo is required for a shielded recipient"),
+            #[cfg(feature = "orchard")]
+            Error::OrchardBuild(err) => err.fmt(f),
+            Error::ChangeError => write!(
+                f,
+                "The transaction requires change, but no change address was supplied"
+            ),
         }
     }
 }
 
+/// The fee and change outputs computed for a transaction by a [`ChangeStrategy`].
+pub struct TransactionBalance {
+    /// The fee that must be paid by the transaction.
+    fee: Amount,
+    /// The change outputs to append to the transaction, one per asset with a
+    /// positive remainder.
+    change: Vec<ChangeValue>,
+}
+
+impl TransactionBalance {
+    pub fn new(fee: Amount, change: Vec<ChangeValue>) -> Self {
+        Self { fee, change }
+    }
+
+    /// Returns the fee computed for the transaction.
+    pub fn fee(&self) -> &Amount {
+        &self.fee
+    }
+
+    /// Returns the change outputs to be appended to the transaction.
+    pub fn change(&self) -> &[ChangeValue] {
+        &self.change
+    }
+}
+
+/// A single change output, returning the positive remainder of one [`AssetType`]
+/// to the caller.
+#[derive(Clone, Debug)]
+pub struct ChangeValue {
+    asset_type: AssetType,
+    value: u64,
+}
+
+impl ChangeValue {
+    pub fn new(asset_type: AssetType, value: u64) -> Self {
+        Self { asset_type, value }
+    }
+
+    pub fn asset_type(&self) -> AssetType {
+        self.asset_type
+    }
+
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+/// A trait for computing the fee and multi-asset change of a transaction from
+/// its current input and output views.
+///
+/// Because the MASP is a multi-asset pool, implementations must operate per
+/// [`AssetType`]: the net balance is computed for every asset appearing among
+/// the inputs, outputs and converts, and a change output is produced for each
+/// asset with a positive remainder after the fee.
+pub trait ChangeStrategy {
+    type FeeError;
+
+    fn compute_balance<P: consensus::Parameters, FR: FeeRule<Error = Self::FeeError>>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::fees::InputView],
+        transparent_outputs: &[impl transparent::fees::OutputView],
+        sapling_inputs: &[impl sapling_fees::InputView<()>],
+        sapling_outputs: &[impl sapling_fees::OutputView],
+        sapling_converts: &[impl sapling_fees::ConvertView],
+        fee_rule: &FR,
+    ) -> Result<TransactionBalance, Error<Self::FeeError>>;
+}
+
+/// The default [`ChangeStrategy`]: a fixed fee drawn from the supplied
+/// [`FeeRule`], with per-asset change returned to the caller's change address.
+pub struct BasicFixedFeeChangeStrategy;
+
+impl ChangeStrategy for BasicFixedFeeChangeStrategy {
+    type FeeError = <fixed::FeeRule as FeeRule>::Error;
+
+    fn compute_balance<P: consensus::Parameters, FR: FeeRule<Error = Self::FeeError>>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::fees::InputView],
+        transparent_outputs: &[impl transparent::fees::OutputView],
+        sapling_inputs: &[impl sapling_fees::InputView<()>],
+        sapling_outputs: &[impl sapling_fees::OutputView],
+        sapling_converts: &[impl sapling_fees::ConvertView],
+        fee_rule: &FR,
+    ) -> Result<TransactionBalance, Error<Self::FeeError>> {
+        let fee = fee_rule
+            .fee_required(
+                params,
+                target_height,
+                transparent_inputs,
+                transparent_outputs,
+                sapling_inputs.len(),
+                sapling_converts.len(),
+                sapling_outputs.len(),
+                // Scope cut: the change strategy operates on the Sapling/transparent
+                // views only and is not passed an Orchard view, so it reports zero
+                // Orchard actions here. The one-shot `build` path prices Orchard
+                // actions correctly via `orchard_action_count`; an Orchard-aware
+                // change strategy would need the fee trait's Orchard count wired
+                // through `ChangeStrategy::compute_balance` as well.
+                0,
+            )
+            .map_err(Error::Fee)?;
+
+        // Accumulate the signed net balance per asset across all three legs, then
+        // treat the fee as an additional (base-asset) output.
+        let mut net: BTreeMap<AssetType, i128> = BTreeMap::new();
+        for input in transparent_inputs {
+            let c = input.coin();
+            *net.entry(c.asset_type).or_default() += c.value;
+        }
+        for output in transparent_outputs {
+            *net.entry(output.asset_type()).or_default() -= output.value();
+        }
+        for input in sapling_inputs {
+            *net.entry(input.asset_type()).or_default() += i128::from(input.value());
+        }
+        for convert in sapling_converts {
+            // A convert applies its allowed conversion `value` times, so each of the
+            // conversion's per-asset amounts contributes scaled by the convert's value.
+            let value = i128::from(convert.value());
+            for (asset, amount) in convert.conversion().components() {
+                *net.entry(*asset).or_default() += *amount * value;
+            }
+        }
+        for output in sapling_outputs {
+            *net.entry(output.asset_type()).or_default() -= i128::from(output.value());
+        }
+        for (asset, value) in fee.components() {
+            *net.entry(*asset).or_default() -= *value;
+        }
+
+        let change = net
+            .into_iter()
+            .filter(|(_, v)| *v > 0)
+            .map(|(asset, v)| ChangeValue::new(asset, v as u64))
+            .collect();
+
+        Ok(TransactionBalance::new(fee, change))
+    }
+}
+
 impl<FE: fmt::Debug + fmt::Display> error::Error for Error<FE> {}
 
 impl<FE> From<BalanceError> for Error<FE> {
@@ -88,10 +288,15 @@ impl<FE> From<BalanceError> for Error<FE> {
 }
 
 /// Reports on the progress made by the builder towards building a transaction.
+///
+/// One update is emitted before each Sapling `spend_proof`/`output_proof` is
+/// computed, so `cur` is the index of the step about to run and `end` (when
+/// known) is the total number of spend + output proofs to compute.
 pub struct Progress {
-    /// The number of steps completed.
+    /// The index of the current step.
     cur: u32,
-    /// The expected total number of steps (as of this progress update), if known.
+    /// The expected total number of spend + output proofs (as of this progress
+    /// update), if known.
     end: Option<u32>,
 }
 
@@ -116,6 +321,30 @@ impl Progress {
     }
 }
 
+/// A parsed recipient of funds, abstracting over shielded and transparent destinations.
+///
+/// This lets wallet code use a single [`Builder::add_output`] entry point regardless of
+/// whether the destination is a Sapling payment address or a transparent address.
+#[derive(Clone, Debug)]
+pub enum RecipientAddress {
+    /// A shielded Sapling payment address.
+    Shielded(PaymentAddress),
+    /// A transparent address.
+    Transparent(TransparentAddress),
+}
+
+impl From<PaymentAddress> for RecipientAddress {
+    fn from(addr: PaymentAddress) -> Self {
+        RecipientAddress::Shielded(addr)
+    }
+}
+
+impl From<TransparentAddress> for RecipientAddress {
+    fn from(addr: TransparentAddress) -> Self {
+        RecipientAddress::Transparent(addr)
+    }
+}
+
 /// Generates a [`Transaction`] from its inputs and outputs.
 #[derive(Clone, Debug, BorshSerialize, BorshDeserialize)]
 pub struct Builder<P, R> {
@@ -125,6 +354,9 @@ pub struct Builder<P, R> {
     expiry_height: BlockHeight,
     transparent_builder: TransparentBuilder,
     sapling_builder: SaplingBuilder<P>,
+    sapling_padding: PaddingRule,
+    #[cfg(feature = "orchard")]
+    orchard_builder: OrchardBuilder,
     #[borsh_skip]
     progress_notifier: Option<Sender<Progress>>,
 }
@@ -210,10 +442,25 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
             expiry_height: target_height + DEFAULT_TX_EXPIRY_DELTA,
             transparent_builder: TransparentBuilder::empty(),
             sapling_builder: SaplingBuilder::new(params, target_height),
+            sapling_padding: PaddingRule::default(),
+            #[cfg(feature = "orchard")]
+            orchard_builder: OrchardBuilder::new(target_height),
             progress_notifier: None,
         }
     }
 
+    /// Sets the padding policy applied to the Sapling bundle during [`Builder::build`].
+    ///
+    /// When a rule of [`PaddingRule::PadTo`] is configured and the bundle contains any
+    /// Sapling spend, convert or output, the build step appends zero-value dummy outputs
+    /// until the output count reaches the target. Each dummy is sent to a freshly generated
+    /// diversified address derived from a throwaway spending key, so it decrypts to nobody,
+    /// and carries the fixed placeholder [`AssetType`] `AssetType::new(b"dummy")` with an
+    /// empty memo; being zero-valued, it leaves every per-asset `value_balance` unchanged.
+    pub fn set_sapling_padding(&mut self, padding: PaddingRule) {
+        self.sapling_padding = padding;
+    }
+
     /// Adds a Sapling note to be spent in this transaction.
     ///
     /// Returns an error if the given Merkle path does not have the same anchor as the
@@ -243,6 +490,55 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
             .add_convert(allowed, value, merkle_path)
     }
 
+    /// Adds an Orchard note to be spent in this transaction.
+    #[cfg(feature = "orchard")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "orchard")))]
+    pub fn add_orchard_spend(
+        &mut self,
+        extsk: ExtendedSpendingKey,
+        note: Note,
+        merkle_path: sapling::MerklePath,
+    ) -> Result<(), orchard_builder::Error> {
+        self.orchard_builder
+            .add_spend(&mut self.rng, extsk, note, merkle_path)
+    }
+
+    /// Adds an Orchard address to send funds to.
+    #[cfg(feature = "orchard")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "orchard")))]
+    pub fn add_orchard_output(
+        &mut self,
+        ovk: Option<OutgoingViewingKey>,
+        to: PaymentAddress,
+        asset_type: AssetType,
+        value: u64,
+        memo: MemoBytes,
+    ) -> Result<(), orchard_builder::Error> {
+        if value > MAX_MONEY.try_into().unwrap() {
+            return Err(orchard_builder::Error::InvalidAmount);
+        }
+        self.orchard_builder.add_output(
+            &mut self.rng,
+            ovk,
+            to,
+            asset_type,
+            NoteValue::from_raw(value.into()),
+            memo,
+        )
+    }
+
+    /// Adds an Orchard multi-asset conversion to this transaction.
+    #[cfg(feature = "orchard")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "orchard")))]
+    pub fn add_orchard_convert(
+        &mut self,
+        allowed: AllowedConversion,
+        value: u64,
+        merkle_path: sapling::MerklePath,
+    ) -> Result<(), orchard_builder::Error> {
+        self.orchard_builder.add_convert(allowed, value, merkle_path)
+    }
+
     /// Adds a Sapling address to send funds to.
     pub fn add_sapling_output(
         &mut self,
@@ -265,14 +561,70 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
         )
     }
 
-    /// Adds a transparent coin to be spent in this transaction.
+    /// Appends zero-value dummy Sapling outputs until the Sapling output count
+    /// reaches the target configured by [`Builder::set_sapling_padding`],
+    /// returning the number of dummies that were added.
+    ///
+    /// Each dummy is sent to a diversified address derived from a throwaway
+    /// spending key drawn from the builder's RNG, carries a zero value and an
+    /// empty memo, and is denominated in the fixed placeholder asset
+    /// `AssetType::new(b"dummy")`, so that it is indistinguishable on-chain from a
+    /// real output while decrypting to nobody.
+    /// No padding is applied when the Sapling bundle is empty, so fully
+    /// transparent transactions stay minimal.
+    fn pad_sapling_outputs(&mut self) -> Result<usize, sapling_builder::Error> {
+        let target = match self.sapling_padding {
+            PaddingRule::None => return Ok(0),
+            PaddingRule::PadTo(target) => target,
+        };
+
+        let sapling_components_present = !self.sapling_builder.inputs().is_empty()
+            || !self.sapling_builder.outputs().is_empty()
+            || !self.sapling_builder.converts().is_empty();
+        if !sapling_components_present {
+            return Ok(0);
+        }
+
+        let existing = self.sapling_builder.outputs().len();
+        if existing >= target {
+            return Ok(0);
+        }
+
+        for _ in existing..target {
+            let mut seed = [0u8; 32];
+            self.rng.fill_bytes(&mut seed);
+            let to = ExtendedSpendingKey::master(&seed)
+                .to_diversifiable_full_viewing_key()
+                .default_address()
+                .1;
+            self.sapling_builder.add_output(
+                &mut self.rng,
+                None,
+                to,
+                AssetType::new(b"dummy").expect("the dummy asset identifier is valid"),
+                NoteValue::from_raw(0),
+                MemoBytes::empty(),
+            )?;
+        }
+
+        Ok(target - existing)
+    }
+
+    /// Adds a transparent UTXO to be spent in this transaction.
+    ///
+    /// The `sk` secret key is retained so that, during `build`/`mock_build`, the legacy
+    /// ECDSA `scriptSig` can be produced for this input by computing the transparent
+    /// sighash per input and signing it with the provided secp256k1 key. This enables
+    /// t→z shielding transactions and mixed spends.
     #[cfg(feature = "transparent-inputs")]
     #[cfg_attr(docsrs, doc(cfg(feature = "transparent-inputs")))]
     pub fn add_transparent_input(
         &mut self,
+        sk: secp256k1::SecretKey,
+        utxo: transparent::OutPoint,
         coin: TxOut,
     ) -> Result<(), transparent::builder::Error> {
-        self.transparent_builder.add_input(coin)
+        self.transparent_builder.add_input(sk, utxo, coin)
     }
 
     /// Adds a transparent address to send funds to.
@@ -286,7 +638,39 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
             return Err(transparent::builder::Error::InvalidAmount);
         }
 
-        self.transparent_builder.add_output(to, asset_type, value)
+        self.transparent_builder
+            .add_output(&self.params, to, asset_type, value)
+    }
+
+    /// Adds an output to the transaction, routing it to the correct internal builder
+    /// based on the kind of recipient address supplied.
+    ///
+    /// A memo may only be attached to a shielded recipient; supplying one for a
+    /// transparent recipient returns [`Error::MemoForbidden`], and omitting one for a
+    /// shielded recipient returns [`Error::MemoRequired`]. The asset type is consistent
+    /// across both shielded and transparent legs.
+    pub fn add_output<FE>(
+        &mut self,
+        ovk: Option<OutgoingViewingKey>,
+        to: &RecipientAddress,
+        asset_type: AssetType,
+        value: u64,
+        memo: Option<MemoBytes>,
+    ) -> Result<(), Error<FE>> {
+        match to {
+            RecipientAddress::Shielded(addr) => {
+                let memo = memo.ok_or(Error::MemoRequired)?;
+                self.add_sapling_output(ovk, *addr, asset_type, value, memo)
+                    .map_err(Error::SaplingBuild)
+            }
+            RecipientAddress::Transparent(addr) => {
+                if memo.is_some() {
+                    return Err(Error::MemoForbidden);
+                }
+                self.add_transparent_output(addr, asset_type, value.into())
+                    .map_err(Error::TransparentBuild)
+            }
+        }
     }
 
     /// Sets the notifier channel, where progress of building the transaction is sent.
@@ -299,11 +683,43 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
         self.progress_notifier = Some(progress_notifier);
     }
 
+    /// Returns the number of Orchard actions the configured Orchard bundle will
+    /// emit, so that the fee rule can account for them. Always zero when the
+    /// `orchard` feature is disabled.
+    fn orchard_action_count(&self) -> usize {
+        #[cfg(feature = "orchard")]
+        {
+            self.orchard_builder.num_actions()
+        }
+        #[cfg(not(feature = "orchard"))]
+        {
+            0
+        }
+    }
+
+    /// Returns the signed net value balance for every [`AssetType`] appearing
+    /// across the transparent, Sapling, and convert components of the builder
+    /// (and the Orchard components when the `orchard` feature is enabled).
+    ///
+    /// A positive entry means the inputs exceed the outputs for that asset (the
+    /// excess must be spent as fee or change); a negative entry means the asset
+    /// is short. This supports multi-asset wallets and conversion flows, where a
+    /// transaction can be balanced in one asset but short in another.
+    pub fn value_balances(&self) -> Result<BTreeMap<AssetType, i128>, BalanceError> {
+        let mut balances: BTreeMap<AssetType, i128> = BTreeMap::new();
+        for (asset, value) in self.value_balance()?.components() {
+            *balances.entry(*asset).or_default() += *value;
+        }
+        Ok(balances)
+    }
+
     /// Returns the sum of the transparent, Sapling, and TZE value balances.
     fn value_balance(&self) -> Result<Amount, BalanceError> {
         let value_balances = [
             self.transparent_builder.value_balance()?,
             self.sapling_builder.value_balance(),
+            #[cfg(feature = "orchard")]
+            self.orchard_builder.value_balance(),
         ];
 
         Ok(value_balances.into_iter().sum::<Amount>())
@@ -327,16 +743,81 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
                 self.sapling_builder.inputs().len(),
                 self.sapling_builder.converts().len(),
                 self.sapling_builder.bundle_output_count(),
+                self.orchard_action_count(),
             )
             .map_err(Error::Fee)?;
         self.build_internal(prover, fee)
     }
 
+    /// Builds a transaction, computing and appending multi-asset change automatically.
+    ///
+    /// The supplied [`ChangeStrategy`] inspects the current input and output views and
+    /// returns a [`TransactionBalance`] describing the fee and one [`ChangeValue`] per
+    /// asset with a positive remainder. Each change value is appended as a Sapling output
+    /// back to `change_address` (optionally carrying `change_memo`), and the transaction is
+    /// then built with the fee reported by the strategy; unlike [`Builder::build`], the fee
+    /// is not recomputed after the change outputs are appended.
+    ///
+    /// Returns [`Error::ChangeError`] if change is required but `change_address` is `None`.
+    pub fn build_with_change<FR: FeeRule, CS: ChangeStrategy<FeeError = FR::Error>>(
+        mut self,
+        prover: &impl TxProver,
+        fee_rule: &FR,
+        change_address: Option<PaymentAddress>,
+        ovk: Option<OutgoingViewingKey>,
+        change_memo: Option<MemoBytes>,
+        strategy: CS,
+    ) -> Result<(Transaction, SaplingMetadata), Error<FR::Error>> {
+        let balance = strategy.compute_balance(
+            &self.params,
+            self.target_height,
+            self.transparent_builder.inputs(),
+            self.transparent_builder.outputs(),
+            self.sapling_builder.inputs(),
+            self.sapling_builder.outputs(),
+            self.sapling_builder.converts(),
+            fee_rule,
+        )?;
+
+        if !balance.change().is_empty() {
+            let to = change_address.ok_or(Error::ChangeError)?;
+            let memo = change_memo.unwrap_or_else(MemoBytes::empty);
+            for change in balance.change() {
+                self.add_sapling_output(
+                    ovk,
+                    to,
+                    change.asset_type(),
+                    change.value(),
+                    memo.clone(),
+                )
+                .map_err(Error::SaplingBuild)?;
+            }
+        }
+
+        self.build_internal(prover, balance.fee)
+    }
+
     fn build_internal<FE>(
         self,
         prover: &impl TxProver,
         fee: Amount,
     ) -> Result<(Transaction, SaplingMetadata), Error<FE>> {
+        let unproven = self.build_unproven(fee)?;
+        // A `TxProver` supplies both the spend and output proving material, so
+        // the one-call path reuses it for both halves of the two-phase API.
+        unproven.prove_and_sign(prover, prover)
+    }
+
+    /// Runs the balance and consistency checks, assembles the unauthorized
+    /// [`TransactionData`], and computes the txid digest parts and the
+    /// `shielded_sig_commitment`, returning an [`UnprovenTransaction`] that
+    /// carries the bundle together with the per-spend proof-generation inputs
+    /// (rcv, alpha, anchors, Merkle paths) *without* invoking any prover.
+    ///
+    /// The returned value is completed with
+    /// [`UnprovenTransaction::prove_and_sign`]; it implements no serialization of
+    /// its own.
+    pub fn build_unproven<FE>(self, fee: Amount) -> Result<UnprovenTransaction, Error<FE>> {
         let consensus_branch_id = BranchId::for_height(&self.params, self.target_height);
 
         // determine transaction version
@@ -346,57 +827,163 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
         // Consistency checks
         //
 
-        // After fees are accounted for, the value balance of the transaction must be zero.
+        // After fees are accounted for, the value balance of the transaction must be zero
+        // for *every* asset. Split the per-asset residual into the assets that are short
+        // (negative) and those with an excess that requires change (positive), so that
+        // the caller receives the full breakdown under the matching error variant.
         let balance_after_fees = self.value_balance()? - fee;
 
-        if balance_after_fees != Amount::zero() {
-            return Err(Error::InsufficientFunds(-balance_after_fees));
+        let mut shortfall = Amount::zero();
+        let mut change_required = Amount::zero();
+        for (asset, value) in balance_after_fees.components() {
+            if *value < 0 {
+                shortfall +=
+                    Amount::from_pair(*asset, -*value).map_err(|_| BalanceError::Overflow)?;
+            } else if *value > 0 {
+                change_required +=
+                    Amount::from_pair(*asset, *value).map_err(|_| BalanceError::Overflow)?;
+            }
+        }
+        if shortfall != Amount::zero() {
+            return Err(Error::InsufficientFunds(shortfall));
+        }
+        if change_required != Amount::zero() {
+            return Err(Error::ChangeRequired(change_required));
         };
 
         let transparent_bundle = self.transparent_builder.build();
 
+        // Record the count of caller-supplied (real) Sapling outputs before
+        // appending any dummies, then pad the bundle up to the configured target.
+        // The dummies are the trailing outputs, so the first `sapling_real_outputs`
+        // positions reported in `SaplingMetadata` are the real ones.
+        let sapling_real_outputs = self.sapling_builder.outputs().len();
+        self.pad_sapling_outputs().map_err(Error::SaplingBuild)?;
+
         let mut rng = self.rng;
-        let mut ctx = prover.new_sapling_proving_context();
-        let sapling_bundle = self
-            .sapling_builder
-            .build(
-                prover,
-                &mut ctx,
-                &mut rng,
-                self.target_height,
-                self.progress_notifier.as_ref(),
-            )
-            .map_err(Error::SaplingBuild)?;
+        // A Sapling bundle (and hence a binding signature) is only produced when the
+        // transaction actually has a shielded value component — a spend, an output, or
+        // a convert. A fully-transparent transaction leaves `sapling_bundle` as `None`,
+        // so no binding signature is emitted and the value-balance commitment stays
+        // correct.
+        let sapling_components_present = !self.sapling_builder.inputs().is_empty()
+            || !self.sapling_builder.outputs().is_empty()
+            || !self.sapling_builder.converts().is_empty();
+
+        // Assemble the unproven Sapling bundle, retaining the proof-generation
+        // inputs so that proofs can be produced on a separate device.
+        let (sapling_bundle, sapling_prover_inputs) = if sapling_components_present {
+            self.sapling_builder
+                .build_unproven(&mut rng, self.target_height)
+                .map_err(Error::SaplingBuild)?
+        } else {
+            (None, sapling_builder::Unproven::empty())
+        };
+
+        // Assemble the unproven Orchard bundle alongside Sapling. As on the
+        // Sapling side, assembly does no proving work, so it takes no progress
+        // notifier; per-action progress is reported from `apply_signatures`
+        // during `prove_and_sign`, where the proofs and signatures are produced
+        // against the shared commitment below.
+        #[cfg(feature = "orchard")]
+        let orchard_bundle = self
+            .orchard_builder
+            .build_unproven(&mut rng, self.target_height)
+            .map_err(Error::OrchardBuild)?;
 
         let unauthed_tx: TransactionData<Unauthorized> = TransactionData {
             version,
-            consensus_branch_id: BranchId::for_height(&self.params, self.target_height),
+            consensus_branch_id,
             lock_time: 0,
             expiry_height: self.expiry_height,
             transparent_bundle,
             sapling_bundle,
+            #[cfg(feature = "orchard")]
+            orchard_bundle,
         };
 
-        //
-        // Signatures -- everything but the signatures must already have been added.
-        //
+        // Precompute the digest parts and the commitment that every Sapling
+        // spend and the binding signature are taken over.
         let txid_parts = unauthed_tx.digest(TxIdDigester);
+        let shielded_sig_commitment =
+            signature_hash(&unauthed_tx, &SignableInput::Shielded, &txid_parts);
+
+        Ok(UnprovenTransaction {
+            unauthed_tx,
+            sapling_prover_inputs,
+            shielded_sig_commitment: *shielded_sig_commitment.as_ref(),
+            sapling_real_outputs,
+            progress_notifier: self.progress_notifier,
+        })
+    }
+}
+
+/// A fully-structured but unproven transaction, as produced by
+/// [`Builder::build_unproven`].
+///
+/// Holding the unauthorized [`TransactionData`] plus the per-spend
+/// proof-generation inputs and the shared `shielded_sig_commitment`, it is
+/// completed via [`UnprovenTransaction::prove_and_sign`] with any
+/// [`SpendProver`]/[`OutputProver`] pair. It implements no serialization and
+/// holds the builder's progress notifier channel.
+pub struct UnprovenTransaction {
+    unauthed_tx: TransactionData<Unauthorized>,
+    sapling_prover_inputs: sapling_builder::Unproven,
+    shielded_sig_commitment: [u8; 32],
+    sapling_real_outputs: usize,
+    progress_notifier: Option<Sender<Progress>>,
+}
+
+impl UnprovenTransaction {
+    /// Returns the commitment that the Sapling spend auth and binding
+    /// signatures are computed over.
+    pub fn shielded_sig_commitment(&self) -> &[u8; 32] {
+        &self.shielded_sig_commitment
+    }
+
+    /// Returns the number of real (caller-supplied) Sapling outputs in this
+    /// transaction. The outputs at positions `0..sapling_real_outputs()` in the
+    /// [`SaplingMetadata`] are real; any remaining outputs are zero-value dummies
+    /// appended by the configured [`PaddingRule`].
+    pub fn sapling_real_outputs(&self) -> usize {
+        self.sapling_real_outputs
+    }
 
+    /// Attaches Sapling spend/output proofs and applies the transparent,
+    /// spend-auth and binding signatures, yielding the final transaction and
+    /// its [`SaplingMetadata`].
+    pub fn prove_and_sign<FE>(
+        self,
+        spend_prover: &impl SpendProver,
+        output_prover: &impl OutputProver,
+    ) -> Result<(Transaction, SaplingMetadata), Error<FE>> {
+        let UnprovenTransaction {
+            unauthed_tx,
+            sapling_prover_inputs,
+            shielded_sig_commitment,
+            sapling_real_outputs: _,
+            progress_notifier,
+        } = self;
+
+        // Produce the legacy ECDSA scriptSig for each transparent input by
+        // computing that input's transparent sighash and signing it with the
+        // secret key supplied to `add_transparent_input`.
+        let txid_parts = unauthed_tx.digest(TxIdDigester);
         let transparent_bundle = unauthed_tx
             .transparent_bundle
             .clone()
-            .map(|b| b.apply_signatures());
-
-        // the commitment being signed is shared across all Sapling inputs; once
-        // V4 transactions are deprecated this should just be the txid, but
-        // for now we need to continue to compute it here.
-        let shielded_sig_commitment =
-            signature_hash(&unauthed_tx, &SignableInput::Shielded, &txid_parts);
+            .map(|b| b.apply_signatures(&unauthed_tx, &txid_parts));
 
         let (sapling_bundle, tx_metadata) = match unauthed_tx
             .sapling_bundle
             .map(|b| {
-                b.apply_signatures(prover, &mut ctx, &mut rng, shielded_sig_commitment.as_ref())
+                b.create_proofs_and_signatures(
+                    spend_prover,
+                    output_prover,
+                    sapling_prover_inputs,
+                    &shielded_sig_commitment,
+                    progress_notifier.as_ref(),
+                )
             })
             .transpose()
             .map_err(Error::SaplingBuild)?
@@ -405,6 +992,16 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
             None => (None, SaplingMetadata::empty()),
         };
 
+        // The Orchard bundle is proven and signed against the same
+        // `shielded_sig_commitment` shared with Sapling, reporting its own
+        // per-action progress through the shared notifier.
+        #[cfg(feature = "orchard")]
+        let orchard_bundle = unauthed_tx
+            .orchard_bundle
+            .map(|b| b.apply_signatures(&shielded_sig_commitment, progress_notifier.as_ref()))
+            .transpose()
+            .map_err(Error::OrchardBuild)?;
+
         let authorized_tx = TransactionData {
             version: unauthed_tx.version,
             consensus_branch_id: unauthed_tx.consensus_branch_id,
@@ -412,6 +1009,8 @@ impl<P: consensus::Parameters, R: RngCore> Builder<P, R> {
             expiry_height: unauthed_tx.expiry_height,
             transparent_bundle,
             sapling_bundle,
+            #[cfg(feature = "orchard")]
+            orchard_bundle,
         };
 
         // The unwrap() here is safe because the txid hashing
@@ -424,6 +1023,12 @@ pub trait MapBuilder<P1, R1, P2, R2>:
     sapling_builder::MapBuilder<P1, P2>
 {
     fn map_rng(&self, s: R1) -> R2;
+
+    /// Carries the Orchard builder through the type transformation.
+    #[cfg(feature = "orchard")]
+    fn map_orchard_builder(&self, orchard_builder: OrchardBuilder) -> OrchardBuilder {
+        orchard_builder
+    }
 }
 
 impl<P1, R1> Builder<P1, R1> {
@@ -437,6 +1042,9 @@ impl<P1, R1> Builder<P1, R1> {
             target_height: self.target_height,
             expiry_height: self.expiry_height,
             transparent_builder: self.transparent_builder,
+            sapling_padding: self.sapling_padding,
+            #[cfg(feature = "orchard")]
+            orchard_builder: f.map_orchard_builder(self.orchard_builder),
             progress_notifier: self.progress_notifier.map(|x| f.map_notifier(x)),
             sapling_builder: self.sapling_builder.map_builder(f),
         }
@@ -496,7 +1104,7 @@ mod tests {
         zip32::ExtendedSpendingKey,
     };
 
-    use super::{Builder, Error};
+    use super::{Builder, Error, PaddingRule};
 
     #[test]
     fn fails_on_overflow_output() {
@@ -531,6 +1139,55 @@ mod tests {
         AssetType::new(b"ZEC").unwrap()
     }
 
+    #[test]
+    fn pads_sapling_outputs_to_target() {
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let dfvk = extsk.to_diversifiable_full_viewing_key();
+        let ovk = dfvk.fvk().ovk;
+        let to = dfvk.default_address().1;
+
+        let tx_height = TEST_NETWORK
+            .activation_height(NetworkUpgrade::MASP)
+            .unwrap();
+        let mut builder = Builder::new(TEST_NETWORK, tx_height);
+        builder.set_sapling_padding(PaddingRule::PadTo(4));
+
+        builder
+            .add_sapling_output(Some(ovk), to, zec(), 1000, MemoBytes::empty())
+            .unwrap();
+        assert_eq!(builder.sapling_builder.outputs().len(), 1);
+
+        // The real-output boundary is the output count captured before padding;
+        // everything appended afterwards is a dummy. This is exactly the value the
+        // build path records as `UnprovenTransaction::sapling_real_outputs`.
+        let real_outputs = builder.sapling_builder.outputs().len();
+        assert_eq!(real_outputs, 1);
+
+        // Padding brings the output count up to the configured target, appending
+        // the difference as trailing dummies without moving the real boundary.
+        assert_eq!(builder.pad_sapling_outputs().unwrap(), 3);
+        assert_eq!(builder.sapling_builder.outputs().len(), 4);
+        assert_eq!(real_outputs, 1);
+
+        // Re-running is idempotent once the target has been reached.
+        assert_eq!(builder.pad_sapling_outputs().unwrap(), 0);
+        assert_eq!(builder.sapling_builder.outputs().len(), 4);
+    }
+
+    #[test]
+    fn does_not_pad_empty_sapling_bundle() {
+        let tx_height = TEST_NETWORK
+            .activation_height(NetworkUpgrade::MASP)
+            .unwrap();
+        let mut builder = Builder::new(TEST_NETWORK, tx_height);
+        builder.set_sapling_padding(PaddingRule::PadTo(4));
+
+        // With no shielded component, a fully transparent transaction is left
+        // minimal rather than acquiring dummy outputs.
+        assert_eq!(builder.pad_sapling_outputs().unwrap(), 0);
+        assert_eq!(builder.sapling_builder.outputs().len(), 0);
+    }
+
     #[test]
     fn binding_sig_present_if_shielded_spend() {
         let extsk = ExtendedSpendingKey::master(&[]);
@@ -0,0 +1,57 @@
+//! A fixed fee rule, retained for backward compatibility with callers that
+//! assume a flat per-transaction fee.
+
+use std::convert::Infallible;
+
+use crate::{
+    consensus::{self, BlockHeight},
+    transaction::components::{
+        amount::{Amount, DEFAULT_FEE},
+        transparent::fees as transparent,
+    },
+};
+
+/// A [`FeeRule`] that returns the same fixed fee regardless of transaction contents.
+///
+/// [`FeeRule`]: super::FeeRule
+#[derive(Clone, Debug)]
+pub struct FeeRule {
+    fixed_fee: Amount,
+}
+
+impl FeeRule {
+    /// Creates a new fixed fee rule with the given fixed fee.
+    pub fn non_standard(fixed_fee: Amount) -> Self {
+        Self { fixed_fee }
+    }
+
+    /// Creates a new fixed fee rule with the standard default fee.
+    pub fn standard() -> Self {
+        Self {
+            fixed_fee: DEFAULT_FEE.clone(),
+        }
+    }
+
+    /// Returns the fixed fee charged by this rule.
+    pub fn fixed_fee(&self) -> Amount {
+        self.fixed_fee.clone()
+    }
+}
+
+impl super::FeeRule for FeeRule {
+    type Error = Infallible;
+
+    fn fee_required<P: consensus::Parameters>(
+        &self,
+        _params: &P,
+        _target_height: BlockHeight,
+        _transparent_inputs: &[impl transparent::InputView],
+        _transparent_outputs: &[impl transparent::OutputView],
+        _sapling_spend_count: usize,
+        _sapling_convert_count: usize,
+        _sapling_output_count: usize,
+        _orchard_action_count: usize,
+    ) -> Result<Amount, Self::Error> {
+        Ok(self.fixed_fee.clone())
+    }
+}
@@ -0,0 +1,37 @@
+//! Abstractions and types related to fee calculations.
+
+use crate::{
+    consensus::{self, BlockHeight},
+    transaction::components::{amount::Amount, transparent::fees as transparent},
+};
+
+pub mod fixed;
+pub mod zip317;
+
+pub use fixed::FeeRule as FixedFeeRule;
+pub use zip317::FeeRule as Zip317FeeRule;
+
+/// A trait that represents the ability to compute the fee required for a
+/// transaction given a description of its inputs and outputs.
+///
+/// The transparent inputs and outputs are passed as views, and the shielded
+/// components as counts rather than the components themselves, so that the same
+/// rule can be evaluated both while the transaction is still being assembled and
+/// after it has been built.
+pub trait FeeRule {
+    type Error;
+
+    /// Computes the fee required for a transaction given the provided inputs and outputs.
+    #[allow(clippy::too_many_arguments)]
+    fn fee_required<P: consensus::Parameters>(
+        &self,
+        params: &P,
+        target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling_spend_count: usize,
+        sapling_convert_count: usize,
+        sapling_output_count: usize,
+        orchard_action_count: usize,
+    ) -> Result<Amount, Self::Error>;
+}
@@ -0,0 +1,84 @@
+//! An implementation of the [ZIP 317] fee rule.
+//!
+//! [ZIP 317]: https://zips.z.cash/zip-0317
+
+use std::cmp::max;
+use std::convert::Infallible;
+
+use crate::{
+    consensus::{self, BlockHeight},
+    transaction::components::{amount::Amount, transparent::fees as transparent},
+};
+
+/// The marginal fee per logical action, in zatoshis.
+pub const MARGINAL_FEE: u64 = 5000;
+
+/// The minimum number of logical actions charged for: a transaction with fewer
+/// logical actions still pays the marginal fee for this many.
+pub const GRACE_ACTIONS: usize = 2;
+
+/// A [`FeeRule`] implementing the ZIP 317 conventional fee.
+///
+/// The conventional fee is `marginal_fee * max(grace_actions, logical_actions)`,
+/// where the logical action count is
+/// `max(n_transparent_in, n_transparent_out) + max(n_sapling_spends, n_sapling_outputs)
+/// + n_orchard_actions`.
+///
+/// [`FeeRule`]: super::FeeRule
+#[derive(Clone, Debug)]
+pub struct FeeRule {
+    marginal_fee: Amount,
+    grace_actions: usize,
+}
+
+impl FeeRule {
+    /// Creates a new ZIP 317 fee rule using the standard marginal fee and grace
+    /// actions, denominated in the given marginal-fee asset.
+    ///
+    /// `marginal_fee` must be an [`Amount`] equal to [`MARGINAL_FEE`] zatoshis of
+    /// the base asset.
+    pub fn standard(marginal_fee: Amount) -> Self {
+        Self {
+            marginal_fee,
+            grace_actions: GRACE_ACTIONS,
+        }
+    }
+
+    /// Creates a ZIP 317 fee rule with a non-standard marginal fee and grace
+    /// action count.
+    pub fn non_standard(marginal_fee: Amount, grace_actions: usize) -> Self {
+        Self {
+            marginal_fee,
+            grace_actions,
+        }
+    }
+}
+
+impl super::FeeRule for FeeRule {
+    type Error = Infallible;
+
+    fn fee_required<P: consensus::Parameters>(
+        &self,
+        _params: &P,
+        _target_height: BlockHeight,
+        transparent_inputs: &[impl transparent::InputView],
+        transparent_outputs: &[impl transparent::OutputView],
+        sapling_spend_count: usize,
+        _sapling_convert_count: usize,
+        sapling_output_count: usize,
+        orchard_action_count: usize,
+    ) -> Result<Amount, Self::Error> {
+        let logical_actions = max(transparent_inputs.len(), transparent_outputs.len())
+            + max(sapling_spend_count, sapling_output_count)
+            + orchard_action_count;
+
+        let actions = max(self.grace_actions, logical_actions);
+
+        // conventional_fee = marginal_fee * max(grace_actions, logical_actions)
+        let mut fee = Amount::zero();
+        for _ in 0..actions {
+            fee += self.marginal_fee.clone();
+        }
+        Ok(fee)
+    }
+}
@@ -16,20 +16,142 @@ use crate::asset_type::AssetType;
 
 pub mod builder;
 pub mod components;
+pub mod fees;
 mod sighash;
 
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod testing;
+
 #[cfg(test)]
 mod tests;
 
 pub use self::sighash::{signature_hash, signature_hash_data, SIGHASH_ALL};
 
 use self::components::{Amount, JSDescription, ConvertDescription, OutputDescription, SpendDescription, TxIn, TxOut};
+use self::components::transparent::{OutPoint, Script};
+
+/// The sequence number used for the input of a coinbase transaction.
+const COINBASE_SEQUENCE: u32 = 0xFFFFFFFF;
 
 const OVERWINTER_VERSION_GROUP_ID: u32 = 0x03C48270;
 const OVERWINTER_TX_VERSION: u32 = 3;
 const SAPLING_VERSION_GROUP_ID: u32 = 0x892F2085;
 const SAPLING_TX_VERSION: u32 = 4;
 
+/// The set of defined transaction formats.
+///
+/// Each variant encapsulates exactly one valid `(overwintered, version,
+/// version_group_id)` combination, so that invalid header combinations are
+/// unrepresentable rather than guarded for at each serialization site.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    BorshSerialize,
+    BorshDeserialize,
+    Serialize,
+    Deserialize,
+)]
+pub enum TxVersion {
+    /// A pre-Overwinter (non-overwintered) transaction of the given version.
+    Sprout(u32),
+    /// An Overwinter v3 transaction.
+    Overwinter,
+    /// A Sapling v4 transaction, as used by the MASP (carries `shielded_converts`).
+    Sapling,
+}
+
+impl TxVersion {
+    /// Reads and parses the header (and, when overwintered, the version group id)
+    /// from the first four-to-eight bytes of a transaction.
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let header = reader.read_u32::<LittleEndian>()?;
+        let overwintered = (header >> 31) == 1;
+        let version = header & 0x7FFFFFFF;
+
+        if overwintered {
+            let version_group_id = reader.read_u32::<LittleEndian>()?;
+            match (version, version_group_id) {
+                (OVERWINTER_TX_VERSION, OVERWINTER_VERSION_GROUP_ID) => Ok(TxVersion::Overwinter),
+                (SAPLING_TX_VERSION, SAPLING_VERSION_GROUP_ID) => Ok(TxVersion::Sapling),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Unknown transaction format",
+                )),
+            }
+        } else {
+            Ok(TxVersion::Sprout(version))
+        }
+    }
+
+    /// Writes the header (and, when overwintered, the version group id) for this
+    /// transaction version.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LittleEndian>(self.header())?;
+        if self.is_overwintered() {
+            writer.write_u32::<LittleEndian>(self.version_group_id())?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether this version sets the overwintered bit.
+    pub fn is_overwintered(&self) -> bool {
+        !matches!(self, TxVersion::Sprout(_))
+    }
+
+    /// Returns the numeric version.
+    pub fn version(&self) -> u32 {
+        match self {
+            TxVersion::Sprout(v) => *v,
+            TxVersion::Overwinter => OVERWINTER_TX_VERSION,
+            TxVersion::Sapling => SAPLING_TX_VERSION,
+        }
+    }
+
+    /// Returns the 32-bit header, with the overwintered bit set as appropriate.
+    pub fn header(&self) -> u32 {
+        let mut header = self.version();
+        if self.is_overwintered() {
+            header |= 1 << 31;
+        }
+        header
+    }
+
+    /// Returns the version group id, or `0` for non-overwintered transactions.
+    pub fn version_group_id(&self) -> u32 {
+        match self {
+            TxVersion::Sprout(_) => 0,
+            TxVersion::Overwinter => OVERWINTER_VERSION_GROUP_ID,
+            TxVersion::Sapling => SAPLING_VERSION_GROUP_ID,
+        }
+    }
+
+    /// Returns whether this version carries an expiry height.
+    pub fn has_expiry_height(&self) -> bool {
+        matches!(self, TxVersion::Overwinter | TxVersion::Sapling)
+    }
+
+    /// Returns whether this version carries a Sapling value-balance section.
+    pub fn has_sapling(&self) -> bool {
+        matches!(self, TxVersion::Sapling)
+    }
+
+    /// Returns whether this version carries a `shielded_converts` section.
+    pub fn has_shielded_converts(&self) -> bool {
+        matches!(self, TxVersion::Sapling)
+    }
+
+    /// Returns whether this version can carry joinsplits.
+    pub fn has_joinsplits(&self) -> bool {
+        self.version() >= 2
+    }
+}
+
 #[derive(
     Clone,
     Copy,
@@ -88,89 +210,132 @@ impl PartialEq for Transaction {
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Hash, PartialEq, Eq, PartialOrd)]
-pub struct TransactionData {
-    pub overwintered: bool,
-    pub version: u32,
-    pub version_group_id: u32,
+/// The transparent inputs and outputs of a transaction.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct TransparentBundle {
     pub vin: Vec<TxIn>,
     pub vout: Vec<TxOut>,
-    pub lock_time: u32,
-    pub expiry_height: u32,
+}
+
+/// The Sapling shielded components of a transaction.
+///
+/// The bundle carries the binding-signature invariant: a bundle with at least
+/// one spend, convert or output must be accompanied by a `binding_sig`, and a
+/// bundle with none of them must not be.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct SaplingBundle {
     pub value_balance: Amount,
     pub shielded_spends: Vec<SpendDescription>,
     pub shielded_converts: Vec<ConvertDescription>,
     pub shielded_outputs: Vec<OutputDescription>,
+    pub binding_sig: Option<Signature>,
+}
+
+/// The Sprout (joinsplit) components of a transaction.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct SproutBundle {
     pub joinsplits: Vec<JSDescription>,
     pub joinsplit_pubkey: Option<[u8; 32]>,
     #[serde(serialize_with = "sserialize_option::<_, SerdeArray<u8, 64>, [u8; 64]>")]
     #[serde(deserialize_with = "sdeserialize_option::<_, SerdeArray<u8, 64>, [u8; 64]>")]
     pub joinsplit_sig: Option<[u8; 64]>,
-    pub binding_sig: Option<Signature>,
 }
 
-impl std::fmt::Debug for TransactionData {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(
-            f,
-            "TransactionData(
-                overwintered = {:?},
-                version = {:?},
-                version_group_id = {:?},
-                vin = {:?},
-                vout = {:?},
-                lock_time = {:?},
-                expiry_height = {:?},
-                value_balance = {:?},
-                shielded_spends = {:?},
-                shielded_outputs = {:?},
-                joinsplits = {:?},
-                joinsplit_pubkey = {:?},
-                binding_sig = {:?})",
-            self.overwintered,
-            self.version,
-            self.version_group_id,
-            self.vin,
-            self.vout,
-            self.lock_time,
-            self.expiry_height,
-            self.value_balance,
-            self.shielded_spends,
-            self.shielded_outputs,
-            self.joinsplits,
-            self.joinsplit_pubkey,
-            self.binding_sig
-        )
-    }
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq, PartialOrd)]
+pub struct TransactionData {
+    pub version: TxVersion,
+    pub lock_time: u32,
+    pub expiry_height: u32,
+    pub transparent_bundle: Option<TransparentBundle>,
+    pub sapling_bundle: Option<SaplingBundle>,
+    pub sprout_bundle: Option<SproutBundle>,
 }
 
 impl TransactionData {
     pub fn new() -> Self {
         TransactionData {
-            overwintered: true,
-            version: SAPLING_TX_VERSION,
-            version_group_id: SAPLING_VERSION_GROUP_ID,
-            vin: vec![],
-            vout: vec![],
+            version: TxVersion::Sapling,
             lock_time: 0,
             expiry_height: 0,
-            value_balance: Amount::zero(),
-            shielded_spends: vec![],
-            shielded_converts: vec![],
-            shielded_outputs: vec![],
-            joinsplits: vec![],
-            joinsplit_pubkey: None,
-            joinsplit_sig: None,
-            binding_sig: None,
+            transparent_bundle: None,
+            sapling_bundle: None,
+            sprout_bundle: None,
         }
     }
 
-    fn header(&self) -> u32 {
-        let mut header = self.version;
-        if self.overwintered {
-            header |= 1 << 31;
+    /// Returns the transparent bundle, or `None` if the transaction has no
+    /// transparent inputs or outputs.
+    pub fn transparent_bundle(&self) -> Option<&TransparentBundle> {
+        self.transparent_bundle.as_ref()
+    }
+
+    /// Returns the Sapling bundle, or `None` if the transaction has no Sapling
+    /// shielded components.
+    pub fn sapling_bundle(&self) -> Option<&SaplingBundle> {
+        self.sapling_bundle.as_ref()
+    }
+
+    /// Returns the Sprout bundle, or `None` if the transaction has no joinsplits.
+    pub fn sprout_bundle(&self) -> Option<&SproutBundle> {
+        self.sprout_bundle.as_ref()
+    }
+
+    /// Returns `true` if this transaction is a coinbase transaction, i.e. it has
+    /// exactly one transparent input referencing the null outpoint.
+    ///
+    /// The input's script encodes the block height and arbitrary data rather than
+    /// a signature, and such inputs must not be signature-verified.
+    pub fn is_coinbase(&self) -> bool {
+        self.transparent_bundle
+            .as_ref()
+            .map(|b| b.vin.len() == 1 && b.vin[0].prevout.is_null())
+            .unwrap_or(false)
+    }
+
+    /// Constructs the single input of a coinbase transaction, encoding the block
+    /// `height` followed by an arbitrary `extra_data` payload into the input
+    /// script. The input references the null outpoint.
+    pub fn coinbase_input(height: u32, extra_data: &[u8]) -> TxIn {
+        let mut script = height.to_le_bytes().to_vec();
+        script.extend_from_slice(extra_data);
+        TxIn {
+            prevout: OutPoint::null(),
+            script_sig: Script(script),
+            sequence: COINBASE_SEQUENCE,
         }
-        header
+    }
+
+    /// Validates the structural consensus rules that apply to a coinbase
+    /// transaction: exactly one input, no joinsplits, and no shielded spends.
+    fn validate_coinbase(&self) -> io::Result<()> {
+        let inputs = self.transparent_bundle.as_ref().map_or(0, |b| b.vin.len());
+        if inputs != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "A coinbase transaction must have exactly one input",
+            ));
+        }
+        if self.sprout_bundle.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "A coinbase transaction must not have joinsplits",
+            ));
+        }
+        if self
+            .sapling_bundle
+            .as_ref()
+            .map_or(false, |b| !b.shielded_spends.is_empty())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "A coinbase transaction must not have shielded spends",
+            ));
+        }
+        Ok(())
+    }
+
+    fn header(&self) -> u32 {
+        self.version.header()
     }
 
     pub fn freeze(self) -> io::Result<Transaction> {
@@ -178,6 +343,103 @@ impl TransactionData {
     }
 }
 
+/// The maximum serialized size, in bytes, of a transaction. Used as the budget
+/// from which per-component allocation caps are derived.
+pub const MAX_TX_BYTES: u64 = 2_000_000;
+
+/// A trait for types whose deserialized count can be safely pre-bounded.
+///
+/// Each implementor reports the maximum number of elements that could possibly
+/// appear in a single valid transaction, derived by dividing [`MAX_TX_BYTES`] by
+/// the minimum serialized size of one element. A bounded reader rejects any
+/// attacker-controlled count exceeding this cap, so that deserialization memory
+/// is provably linear in the input length rather than in the claimed count.
+pub trait TrustedPreallocate {
+    /// The maximum number of elements of this type that can appear in one transaction.
+    fn max_allocation() -> u64;
+}
+
+impl TrustedPreallocate for TxIn {
+    fn max_allocation() -> u64 {
+        // A transparent input is at least 41 bytes (36-byte outpoint + 1-byte
+        // empty script + 4-byte sequence).
+        MAX_TX_BYTES / 41
+    }
+}
+
+impl TrustedPreallocate for TxOut {
+    fn max_allocation() -> u64 {
+        // value (8) + asset type (32) + minimal script (1).
+        MAX_TX_BYTES / 41
+    }
+}
+
+impl TrustedPreallocate for SpendDescription {
+    fn max_allocation() -> u64 {
+        // A Sapling spend description is at least 384 bytes.
+        MAX_TX_BYTES / 384
+    }
+}
+
+impl TrustedPreallocate for ConvertDescription {
+    fn max_allocation() -> u64 {
+        // A convert description is at least 320 bytes.
+        MAX_TX_BYTES / 320
+    }
+}
+
+impl TrustedPreallocate for OutputDescription {
+    fn max_allocation() -> u64 {
+        // A Sapling output description is at least 948 bytes.
+        MAX_TX_BYTES / 948
+    }
+}
+
+impl TrustedPreallocate for JSDescription {
+    fn max_allocation() -> u64 {
+        // A JoinSplit description is at least 1602 bytes.
+        MAX_TX_BYTES / 1602
+    }
+}
+
+/// Reads a compact-size-prefixed vector, rejecting any count that exceeds the
+/// element type's [`TrustedPreallocate::max_allocation`] cap before allocating.
+fn read_bounded<R: Read, T: TrustedPreallocate, F>(
+    reader: &mut R,
+    mut func: F,
+) -> io::Result<Vec<T>>
+where
+    F: FnMut(&mut R) -> io::Result<T>,
+{
+    let count = read_compactsize(reader)?;
+    if count > T::max_allocation() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Vector length exceeds maximum allocation for element type",
+        ));
+    }
+
+    // `count` is bounded, so this allocation is safe; elements are still read
+    // one at a time so a truncated input fails before the full capacity is used.
+    let mut items = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        items.push(func(reader)?);
+    }
+    Ok(items)
+}
+
+/// Reads a Bitcoin-style compact size (variable-length) integer.
+fn read_compactsize<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let flag = reader.read_u8()?;
+    let value = match flag {
+        0..=0xFC => u64::from(flag),
+        0xFD => u64::from(reader.read_u16::<LittleEndian>()?),
+        0xFE => u64::from(reader.read_u32::<LittleEndian>()?),
+        _ => reader.read_u64::<LittleEndian>()?,
+    };
+    Ok(value)
+}
+
 impl Transaction {
     fn from_data(data: TransactionData) -> io::Result<Self> {
         let mut tx = Transaction {
@@ -197,51 +459,31 @@ impl Transaction {
     }
 
     pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
-        let header = reader.read_u32::<LittleEndian>()?;
-        let overwintered = (header >> 31) == 1;
-        let version = header & 0x7FFFFFFF;
-
-        let version_group_id = if overwintered {
-            reader.read_u32::<LittleEndian>()?
-        } else {
-            0
-        };
+        let version = TxVersion::read(reader)?;
 
-        let is_overwinter_v3 = overwintered
-            && version_group_id == OVERWINTER_VERSION_GROUP_ID
-            && version == OVERWINTER_TX_VERSION;
-        let is_sapling_v4 = overwintered
-            && version_group_id == SAPLING_VERSION_GROUP_ID
-            && version == SAPLING_TX_VERSION;
-        if overwintered && !(is_overwinter_v3 || is_sapling_v4) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Unknown transaction format",
-            ));
-        }
-
-        let vin = Vector::read(reader, TxIn::read)?;
-        let vout = Vector::read(reader, TxOut::read)?;
+        let vin = read_bounded(reader, TxIn::read)?;
+        let vout = read_bounded(reader, TxOut::read)?;
         let lock_time = reader.read_u32::<LittleEndian>()?;
-        let expiry_height = if is_overwinter_v3 || is_sapling_v4 {
+        let expiry_height = if version.has_expiry_height() {
             reader.read_u32::<LittleEndian>()?
         } else {
             0
         };
 
-        let (value_balance, shielded_spends, shielded_converts, shielded_outputs) = if is_sapling_v4 {
-            let vb = Amount::read(reader)?;
-            let ss = Vector::read(reader, SpendDescription::read)?;
-            let sc = Vector::read(reader, ConvertDescription::read)?;
-            let so = Vector::read(reader, OutputDescription::read)?;
-            (vb, ss, sc, so)
-        } else {
-            (Amount::zero(), vec![], vec![], vec![])
-        };
+        let (value_balance, shielded_spends, shielded_converts, shielded_outputs) =
+            if version.has_sapling() {
+                let vb = Amount::read(reader)?;
+                let ss = read_bounded(reader, SpendDescription::read)?;
+                let sc = read_bounded(reader, ConvertDescription::read)?;
+                let so = read_bounded(reader, OutputDescription::read)?;
+                (vb, ss, sc, so)
+            } else {
+                (Amount::zero(), vec![], vec![], vec![])
+            };
 
-        let (joinsplits, joinsplit_pubkey, joinsplit_sig) = if version >= 2 {
-            let jss = Vector::read(reader, |r| {
-                JSDescription::read(r, overwintered && version >= SAPLING_TX_VERSION)
+        let (joinsplits, joinsplit_pubkey, joinsplit_sig) = if version.has_joinsplits() {
+            let jss = read_bounded(reader, |r| {
+                JSDescription::read(r, version.has_sapling())
             })?;
             let (pubkey, sig) = if !jss.is_empty() {
                 let mut joinsplit_pubkey = [0; 32];
@@ -257,122 +499,317 @@ impl Transaction {
             (vec![], None, None)
         };
 
-        let binding_sig =
-            if is_sapling_v4 && !(shielded_spends.is_empty() && shielded_outputs.is_empty()) {
-                Some(Signature::read(reader)?)
-            } else {
-                None
-            };
+        let binding_sig = if version.has_sapling()
+            && !(shielded_spends.is_empty()
+                && shielded_converts.is_empty()
+                && shielded_outputs.is_empty())
+        {
+            Some(Signature::read(reader)?)
+        } else {
+            None
+        };
+
+        // Fold each pool's components into an `Option` bundle, leaving `None`
+        // when the pool is unused so that downstream code need not inspect
+        // emptiness to know which pools are active.
+        let transparent_bundle = if vin.is_empty() && vout.is_empty() {
+            None
+        } else {
+            Some(TransparentBundle { vin, vout })
+        };
 
-        Transaction::from_data(TransactionData {
-            overwintered,
+        let sapling_bundle = if shielded_spends.is_empty()
+            && shielded_converts.is_empty()
+            && shielded_outputs.is_empty()
+            && value_balance == Amount::zero()
+        {
+            None
+        } else {
+            Some(SaplingBundle {
+                value_balance,
+                shielded_spends,
+                shielded_converts,
+                shielded_outputs,
+                binding_sig,
+            })
+        };
+
+        let sprout_bundle = if joinsplits.is_empty() {
+            None
+        } else {
+            Some(SproutBundle {
+                joinsplits,
+                joinsplit_pubkey,
+                joinsplit_sig,
+            })
+        };
+
+        let data = TransactionData {
             version,
-            version_group_id,
-            vin,
-            vout,
             lock_time,
             expiry_height,
-            value_balance,
-            shielded_spends,
-            shielded_converts,
-            shielded_outputs,
-            joinsplits,
-            joinsplit_pubkey,
-            joinsplit_sig,
-            binding_sig,
-        })
+            transparent_bundle,
+            sapling_bundle,
+            sprout_bundle,
+        };
+
+        // Enforce the coinbase consensus rules at parse time so that callers can
+        // distinguish minted/issuance transactions without re-parsing scripts.
+        if data.is_coinbase() {
+            data.validate_coinbase()?;
+        }
+
+        Transaction::from_data(data)
     }
 
     pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        writer.write_u32::<LittleEndian>(self.header())?;
-        if self.overwintered {
-            writer.write_u32::<LittleEndian>(self.version_group_id)?;
+        if self.is_coinbase() {
+            self.validate_coinbase()?;
         }
 
-        let is_overwinter_v3 = self.overwintered
-            && self.version_group_id == OVERWINTER_VERSION_GROUP_ID
-            && self.version == OVERWINTER_TX_VERSION;
-        let is_sapling_v4 = self.overwintered
-            && self.version_group_id == SAPLING_VERSION_GROUP_ID
-            && self.version == SAPLING_TX_VERSION;
-        if self.overwintered && !(is_overwinter_v3 || is_sapling_v4) {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Unknown transaction format",
-            ));
-        }
+        self.version.write(writer)?;
 
-        Vector::write(writer, &self.vin, |w, e| e.write(w))?;
-        Vector::write(writer, &self.vout, |w, e| e.write(w))?;
+        // The transparent vectors are always present on the wire, even when the
+        // bundle is absent, in which case they are written empty.
+        match self.transparent_bundle() {
+            Some(b) => {
+                Vector::write(writer, &b.vin, |w, e| e.write(w))?;
+                Vector::write(writer, &b.vout, |w, e| e.write(w))?;
+            }
+            None => {
+                Vector::write(writer, &[] as &[TxIn], |w, e| e.write(w))?;
+                Vector::write(writer, &[] as &[TxOut], |w, e| e.write(w))?;
+            }
+        }
         writer.write_u32::<LittleEndian>(self.lock_time)?;
-        if is_overwinter_v3 || is_sapling_v4 {
+        if self.version.has_expiry_height() {
             writer.write_u32::<LittleEndian>(self.expiry_height)?;
         }
 
-        if is_sapling_v4 {
-            self.value_balance.write(writer)?;
-            Vector::write(writer, &self.shielded_spends, |w, e| e.write(w))?;
-            Vector::write(writer, &self.shielded_converts, |w, e| e.write(w))?;
-            Vector::write(writer, &self.shielded_outputs, |w, e| e.write(w))?;
+        if self.version.has_sapling() {
+            match self.sapling_bundle() {
+                Some(b) => {
+                    b.value_balance.write(writer)?;
+                    Vector::write(writer, &b.shielded_spends, |w, e| e.write(w))?;
+                    Vector::write(writer, &b.shielded_converts, |w, e| e.write(w))?;
+                    Vector::write(writer, &b.shielded_outputs, |w, e| e.write(w))?;
+                }
+                None => {
+                    Amount::zero().write(writer)?;
+                    Vector::write(writer, &[] as &[SpendDescription], |w, e| e.write(w))?;
+                    Vector::write(writer, &[] as &[ConvertDescription], |w, e| e.write(w))?;
+                    Vector::write(writer, &[] as &[OutputDescription], |w, e| e.write(w))?;
+                }
+            }
         }
 
-        if self.version >= 2 {
-            Vector::write(writer, &self.joinsplits, |w, e| e.write(w))?;
-            if !self.joinsplits.is_empty() {
-                match self.joinsplit_pubkey {
-                    Some(pubkey) => writer.write_all(&pubkey)?,
-                    None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidInput,
-                            "Missing JoinSplit pubkey",
-                        ));
+        if self.version.has_joinsplits() {
+            match self.sprout_bundle() {
+                Some(b) => {
+                    Vector::write(writer, &b.joinsplits, |w, e| e.write(w))?;
+                    if !b.joinsplits.is_empty() {
+                        match b.joinsplit_pubkey {
+                            Some(pubkey) => writer.write_all(&pubkey)?,
+                            None => {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    "Missing JoinSplit pubkey",
+                                ));
+                            }
+                        }
+                        match b.joinsplit_sig {
+                            Some(sig) => writer.write_all(&sig)?,
+                            None => {
+                                return Err(io::Error::new(
+                                    io::ErrorKind::InvalidInput,
+                                    "Missing JoinSplit signature",
+                                ));
+                            }
+                        }
                     }
                 }
-                match self.joinsplit_sig {
-                    Some(sig) => writer.write_all(&sig)?,
+                None => {
+                    Vector::write(writer, &[] as &[JSDescription], |w, e| e.write(w))?;
+                }
+            }
+        }
+
+        // The binding-signature present/absent invariant is now a property of
+        // the Sapling bundle rather than an ad-hoc check.
+        if let Some(b) = self.sapling_bundle() {
+            if !(b.shielded_spends.is_empty()
+                && b.shielded_converts.is_empty()
+                && b.shielded_outputs.is_empty())
+            {
+                match b.binding_sig {
+                    Some(sig) => sig.write(writer)?,
                     None => {
                         return Err(io::Error::new(
                             io::ErrorKind::InvalidInput,
-                            "Missing JoinSplit signature",
+                            "Missing binding signature",
                         ));
                     }
                 }
-            }
-        }
-
-        if self.version < 2 || self.joinsplits.is_empty() {
-            if self.joinsplit_pubkey.is_some() {
+            } else if b.binding_sig.is_some() {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
-                    "JoinSplit pubkey should not be present",
+                    "Binding signature should not be present",
                 ));
             }
-            if self.joinsplit_sig.is_some() {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "JoinSplit signature should not be present",
-                ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The 12-byte prefix of the top-level non-malleable txid personalization. The
+/// 4-byte consensus branch id is appended to it to form the full 16-byte
+/// personalization, so that an id computed for one branch cannot be replayed on
+/// another.
+const ZCASH_TX_PERSONALIZATION_PREFIX: &[u8; 12] = b"ZcashTxHash_";
+const ZCASH_HEADERS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdHeadersHash";
+const ZCASH_TRANSPARENT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdTranspaHash";
+const ZCASH_PREVOUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdPrevoutHash";
+const ZCASH_SEQUENCE_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSequencHash";
+const ZCASH_OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOutputsHash";
+const ZCASH_SAPLING_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSaplingHash";
+const ZCASH_SAPLING_SPENDS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSSpendsHash";
+const ZCASH_SAPLING_OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSOutputHash";
+/// Personalization for the MASP `shielded_converts` sub-digest. This is a new
+/// constant defined for the multi-asset pool so that convert descriptions are
+/// authenticated by the non-malleable txid.
+const MASP_SAPLING_CONVERTS_HASH_PERSONALIZATION: &[u8; 16] = b"MASPConvertsHash";
+
+fn hasher(personalization: &[u8; 16]) -> blake2b_simd::State {
+    blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(personalization)
+        .to_state()
+}
+
+/// Builds the top-level txid personalization by appending the little-endian
+/// consensus branch id to [`ZCASH_TX_PERSONALIZATION_PREFIX`].
+fn tx_personalization(consensus_branch_id: u32) -> [u8; 16] {
+    let mut personal = [0u8; 16];
+    personal[..12].copy_from_slice(ZCASH_TX_PERSONALIZATION_PREFIX);
+    personal[12..].copy_from_slice(&consensus_branch_id.to_le_bytes());
+    personal
+}
+
+impl Transaction {
+    /// Computes the non-malleable transaction id, per a ZIP-244-style tree of
+    /// BLAKE2b-256 digests.
+    ///
+    /// Each section (header, transparent, Sapling) is digested under its own
+    /// personalization string, and the Sapling digest is in turn built from
+    /// sub-digests over the spends, converts, outputs and value balance. Because
+    /// MASP adds a `shielded_converts` section, a dedicated personalization
+    /// ([`MASP_SAPLING_CONVERTS_HASH_PERSONALIZATION`]) authenticates it.
+    ///
+    /// Signatures (the binding signature and per-spend spend-auth signatures) are
+    /// excluded from the preimage, so they no longer affect the id. The top-level
+    /// digest is personalized with `consensus_branch_id` so that an id is only
+    /// valid on the branch it was computed for. The legacy double-SHA256 id
+    /// computed by [`Transaction::txid`] remains available for old-format
+    /// transactions.
+    pub fn txid_nonmalleable(&self, consensus_branch_id: u32) -> io::Result<TxId> {
+        let header_digest = self.header_digest()?;
+        let transparent_digest = self.transparent_digest()?;
+        let sapling_digest = self.sapling_digest()?;
+
+        let mut h = hasher(&tx_personalization(consensus_branch_id));
+        h.update(header_digest.as_bytes());
+        h.update(transparent_digest.as_bytes());
+        h.update(sapling_digest.as_bytes());
+
+        let mut txid = [0u8; 32];
+        txid.copy_from_slice(h.finalize().as_bytes());
+        Ok(TxId(txid))
+    }
+
+    /// Computes the signature hash for the given input, reusing the same section
+    /// digests as [`Transaction::txid_nonmalleable`] plus a per-input digest, and
+    /// the same branch-id-dependent top-level personalization.
+    pub fn sighash_nonmalleable(
+        &self,
+        consensus_branch_id: u32,
+        per_input_digest: &[u8; 32],
+    ) -> io::Result<[u8; 32]> {
+        let header_digest = self.header_digest()?;
+        let transparent_digest = self.transparent_digest()?;
+        let sapling_digest = self.sapling_digest()?;
+
+        let mut h = hasher(&tx_personalization(consensus_branch_id));
+        h.update(header_digest.as_bytes());
+        h.update(transparent_digest.as_bytes());
+        h.update(sapling_digest.as_bytes());
+        h.update(per_input_digest);
+
+        let mut sighash = [0u8; 32];
+        sighash.copy_from_slice(h.finalize().as_bytes());
+        Ok(sighash)
+    }
+
+    fn header_digest(&self) -> io::Result<blake2b_simd::Hash> {
+        let mut h = hasher(ZCASH_HEADERS_HASH_PERSONALIZATION);
+        h.write_u32::<LittleEndian>(self.version.version())?;
+        h.write_u32::<LittleEndian>(self.version.version_group_id())?;
+        h.write_u32::<LittleEndian>(self.lock_time)?;
+        h.write_u32::<LittleEndian>(self.expiry_height)?;
+        Ok(h.finalize())
+    }
+
+    fn transparent_digest(&self) -> io::Result<blake2b_simd::Hash> {
+        let mut prevouts = hasher(ZCASH_PREVOUTS_HASH_PERSONALIZATION);
+        let mut sequence = hasher(ZCASH_SEQUENCE_HASH_PERSONALIZATION);
+        let mut outputs = hasher(ZCASH_OUTPUTS_HASH_PERSONALIZATION);
+
+        if let Some(bundle) = self.transparent_bundle() {
+            // Only the outpoint and the sequence number are hashed, so the
+            // transparent `script_sig` (which carries signatures) is excluded.
+            for txin in &bundle.vin {
+                txin.prevout.write(&mut prevouts)?;
+                sequence.write_u32::<LittleEndian>(txin.sequence)?;
+            }
+            for txout in &bundle.vout {
+                txout.write(&mut outputs)?;
             }
         }
 
-        if is_sapling_v4 && !(self.shielded_spends.is_empty() && self.shielded_outputs.is_empty()) {
-            match self.binding_sig {
-                Some(sig) => sig.write(writer)?,
-                None => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "Missing binding signature",
-                    ));
-                }
+        let mut h = hasher(ZCASH_TRANSPARENT_HASH_PERSONALIZATION);
+        h.update(prevouts.finalize().as_bytes());
+        h.update(sequence.finalize().as_bytes());
+        h.update(outputs.finalize().as_bytes());
+        Ok(h.finalize())
+    }
+
+    fn sapling_digest(&self) -> io::Result<blake2b_simd::Hash> {
+        let mut spends = hasher(ZCASH_SAPLING_SPENDS_HASH_PERSONALIZATION);
+        let mut converts = hasher(MASP_SAPLING_CONVERTS_HASH_PERSONALIZATION);
+        let mut outputs = hasher(ZCASH_SAPLING_OUTPUTS_HASH_PERSONALIZATION);
+
+        let mut value_balance = Amount::zero();
+        if let Some(bundle) = self.sapling_bundle() {
+            value_balance = bundle.value_balance.clone();
+            // The spend-auth signatures are not part of the spend serialization
+            // fed here, so they do not affect the resulting id.
+            for spend in &bundle.shielded_spends {
+                spend.write(&mut spends)?;
+            }
+            for convert in &bundle.shielded_converts {
+                convert.write(&mut converts)?;
+            }
+            for output in &bundle.shielded_outputs {
+                output.write(&mut outputs)?;
             }
-        } else if self.binding_sig.is_some() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "Binding signature should not be present",
-            ));
         }
 
-        Ok(())
+        let mut h = hasher(ZCASH_SAPLING_HASH_PERSONALIZATION);
+        h.update(spends.finalize().as_bytes());
+        h.update(converts.finalize().as_bytes());
+        h.update(outputs.finalize().as_bytes());
+        value_balance.write(&mut h)?;
+        Ok(h.finalize())
     }
 }
 
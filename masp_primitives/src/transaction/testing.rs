@@ -0,0 +1,136 @@
+//! `proptest` strategies for generating structurally-valid transactions.
+//!
+//! These are exported under the `test-dependencies` feature so that downstream
+//! crates can reuse the generator surface in their own transaction tests. The
+//! strategies only produce transactions whose component presence/absence is
+//! internally consistent — in particular the binding signature, the joinsplit
+//! pubkey/signature, and the version-appropriate sections.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::transaction::{
+    components::{
+        amount::testing::arb_amount,
+        transparent::testing::{arb_txin, arb_txout},
+    },
+    SaplingBundle, SproutBundle, Transaction, TransactionData, TransparentBundle, TxVersion,
+};
+
+// Per-component generators for the shielded descriptions live alongside the
+// components themselves; re-export them here so the whole generator surface is
+// reachable from a single module.
+pub use crate::transaction::components::{
+    convert::testing::arb_convert_description, sapling::testing::arb_output_description,
+    sapling::testing::arb_spend_description, sprout::testing::arb_js_description,
+};
+
+prop_compose! {
+    /// Generates an arbitrary Sapling-format [`TxVersion`].
+    ///
+    /// The serialization round-trip is only defined for overwintered formats, so
+    /// the Sapling (MASP) version is used.
+    pub fn arb_tx_version()(_ in Just(())) -> TxVersion {
+        TxVersion::Sapling
+    }
+}
+
+prop_compose! {
+    /// Generates a transparent bundle, returning `None` when it would be empty.
+    pub fn arb_transparent_bundle()(
+        vin in vec(arb_txin(), 0..4),
+        vout in vec(arb_txout(), 0..4),
+    ) -> Option<TransparentBundle> {
+        if vin.is_empty() && vout.is_empty() {
+            None
+        } else {
+            Some(TransparentBundle { vin, vout })
+        }
+    }
+}
+
+prop_compose! {
+    /// Generates a Sapling bundle whose `binding_sig` is present exactly when the
+    /// bundle carries at least one spend, convert, or output.
+    pub fn arb_sapling_bundle()(
+        value_balance in arb_amount(),
+        shielded_spends in vec(arb_spend_description(), 0..3),
+        shielded_converts in vec(arb_convert_description(), 0..3),
+        shielded_outputs in vec(arb_output_description(), 0..3),
+    )(
+        binding_sig in if shielded_spends.is_empty() && shielded_converts.is_empty() && shielded_outputs.is_empty() {
+            Just(None).boxed()
+        } else {
+            crate::redjubjub::testing::arb_signature().prop_map(Some).boxed()
+        },
+        value_balance in Just(value_balance),
+        shielded_spends in Just(shielded_spends),
+        shielded_converts in Just(shielded_converts),
+        shielded_outputs in Just(shielded_outputs),
+    ) -> Option<SaplingBundle> {
+        if shielded_spends.is_empty()
+            && shielded_converts.is_empty()
+            && shielded_outputs.is_empty()
+            && value_balance == crate::transaction::components::amount::Amount::zero()
+        {
+            None
+        } else {
+            Some(SaplingBundle {
+                value_balance,
+                shielded_spends,
+                shielded_converts,
+                shielded_outputs,
+                binding_sig,
+            })
+        }
+    }
+}
+
+prop_compose! {
+    /// Generates a Sprout bundle, returning `None` when there are no joinsplits and
+    /// keeping the pubkey/signature present exactly when there is at least one.
+    pub fn arb_sprout_bundle()(
+        joinsplits in vec(arb_js_description(), 0..2),
+        joinsplit_pubkey in any::<[u8; 32]>(),
+        joinsplit_sig in any::<[u8; 64]>(),
+    ) -> Option<SproutBundle> {
+        if joinsplits.is_empty() {
+            None
+        } else {
+            Some(SproutBundle {
+                joinsplits,
+                joinsplit_pubkey: Some(joinsplit_pubkey),
+                joinsplit_sig: Some(joinsplit_sig),
+            })
+        }
+    }
+}
+
+prop_compose! {
+    /// Generates an arbitrary, structurally-valid [`TransactionData`].
+    pub fn arb_transaction_data()(
+        version in arb_tx_version(),
+        lock_time in any::<u32>(),
+        expiry_height in any::<u32>(),
+        transparent_bundle in arb_transparent_bundle(),
+        sapling_bundle in arb_sapling_bundle(),
+        sprout_bundle in arb_sprout_bundle(),
+    ) -> TransactionData {
+        TransactionData {
+            version,
+            lock_time,
+            expiry_height,
+            transparent_bundle,
+            sapling_bundle,
+            sprout_bundle,
+        }
+    }
+}
+
+prop_compose! {
+    /// Generates an arbitrary [`Transaction`] by freezing an arbitrary
+    /// [`TransactionData`], computing its txid.
+    pub fn arb_transaction()(data in arb_transaction_data()) -> Transaction {
+        data.freeze().expect("freezing an arbitrary transaction should be infallible")
+    }
+}
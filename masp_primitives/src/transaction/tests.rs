@@ -0,0 +1,104 @@
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use super::components::amount::Amount;
+use super::testing::{arb_convert_description, arb_transaction};
+use super::{SaplingBundle, Transaction, TransactionData, TxId, TxVersion};
+
+proptest! {
+    /// Serializing a transaction and reading it back must reproduce both the
+    /// transaction and its txid, guarding the `read`/`write` byte format against
+    /// regressions — in particular MASP's `shielded_converts` section.
+    #[test]
+    fn transaction_read_write_roundtrip(tx in arb_transaction()) {
+        let mut data = vec![];
+        tx.write(&mut data).unwrap();
+
+        let parsed = Transaction::read(&mut &data[..]).unwrap();
+        prop_assert_eq!(parsed.txid(), tx.txid());
+
+        let mut reencoded = vec![];
+        parsed.write(&mut reencoded).unwrap();
+        prop_assert_eq!(reencoded, data);
+    }
+
+    /// A convert-only transaction must carry a binding signature (its converts
+    /// commit value that the binding signature balances) and must round-trip
+    /// with that signature and its converts preserved.
+    #[test]
+    fn convert_only_transaction_roundtrip(
+        shielded_converts in vec(arb_convert_description(), 1..3),
+        binding_sig in crate::redjubjub::testing::arb_signature(),
+    ) {
+        let data = TransactionData {
+            version: TxVersion::Sapling,
+            lock_time: 0,
+            expiry_height: 0,
+            transparent_bundle: None,
+            sapling_bundle: Some(SaplingBundle {
+                value_balance: Amount::zero(),
+                shielded_spends: vec![],
+                shielded_converts,
+                shielded_outputs: vec![],
+                binding_sig: Some(binding_sig),
+            }),
+            sprout_bundle: None,
+        };
+
+        let tx = data.freeze().unwrap();
+
+        let mut bytes = vec![];
+        tx.write(&mut bytes).unwrap();
+        let parsed = Transaction::read(&mut &bytes[..]).unwrap();
+
+        prop_assert_eq!(parsed.txid(), tx.txid());
+        let bundle = parsed.sapling_bundle().expect("convert-only tx has a Sapling bundle");
+        prop_assert!(bundle.binding_sig.is_some());
+        prop_assert_eq!(bundle.shielded_converts.len(), tx.sapling_bundle().unwrap().shielded_converts.len());
+    }
+
+    /// A convert-only bundle whose value commitments are unbalanced without a
+    /// binding signature must be rejected by `write`, since the converts would
+    /// otherwise be left malleable.
+    #[test]
+    fn convert_only_missing_binding_sig_is_rejected(
+        shielded_converts in vec(arb_convert_description(), 1..3),
+    ) {
+        // `freeze` would itself fail here (it serializes to compute the txid), so
+        // the transaction is assembled directly in order to exercise `write`.
+        let tx = Transaction {
+            txid: TxId([0; 32]),
+            data: TransactionData {
+                version: TxVersion::Sapling,
+                lock_time: 0,
+                expiry_height: 0,
+                transparent_bundle: None,
+                sapling_bundle: Some(SaplingBundle {
+                    value_balance: Amount::zero(),
+                    shielded_spends: vec![],
+                    shielded_converts,
+                    shielded_outputs: vec![],
+                    binding_sig: None,
+                }),
+                sprout_bundle: None,
+            },
+        };
+
+        prop_assert!(tx.write(&mut vec![]).is_err());
+    }
+
+    /// The non-malleable txid must be domain-separated by consensus branch id:
+    /// the same transaction hashed under two different branches must produce
+    /// different ids, so an id cannot be replayed across a network upgrade.
+    #[test]
+    fn txid_nonmalleable_is_branch_separated(
+        tx in arb_transaction(),
+        branch_a in any::<u32>(),
+        branch_b in any::<u32>(),
+    ) {
+        prop_assume!(branch_a != branch_b);
+        let id_a = tx.txid_nonmalleable(branch_a).unwrap();
+        let id_b = tx.txid_nonmalleable(branch_b).unwrap();
+        prop_assert_ne!(id_a, id_b);
+    }
+}